@@ -1,5 +1,5 @@
 //! HDMI CEC driver for STM32 microcontrollers
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![cfg_attr(docsrs, feature(doc_cfg), feature(doc_auto_cfg))]
 
 mod reg;
@@ -125,13 +125,260 @@ pub mod irq {
         | RXBR;
 }
 
+/// Errors that can occur while transmitting a CEC message.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxError {
+    /// Arbitration was lost.
+    ///
+    /// The CEC device switched to reception because another device started
+    /// transmitting earlier, or at the same time with a higher-priority
+    /// header.
+    ///
+    /// Corresponds to [`irq::ARBLST`].
+    ArbitrationLost,
+    /// Low impedance was detected on the CEC line while it was released.
+    ///
+    /// Corresponds to [`irq::TXERR`].
+    Error,
+    /// The application did not write the next byte to `TXDR` in time.
+    ///
+    /// Corresponds to [`irq::TXUDR`].
+    Underrun,
+    /// No acknowledge was received, or a negative acknowledge was received
+    /// for a broadcast message.
+    ///
+    /// Corresponds to [`irq::TXACK`].
+    Nack,
+    /// A blocking call gave up waiting for the peripheral to report an
+    /// event.
+    ///
+    /// Not a hardware-reported error; only produced by [`Cec`]'s blocking
+    /// helpers, never by [`CecState::on_interrupt`].
+    Timeout,
+    /// A message was already being transmitted.
+    ///
+    /// Returned instead of panicking when a transmission started through
+    /// [`Cec::state`] (e.g. from an interrupt handler) is still in flight.
+    Busy,
+}
+
+impl TxError {
+    /// Decode the first matching TX error out of a raw interrupt status
+    /// value, or `None` if it carries no TX error flags.
+    fn from_isr(isr: u32) -> Option<Self> {
+        if isr & irq::ARBLST == irq::ARBLST {
+            Some(Self::ArbitrationLost)
+        } else if isr & irq::TXERR == irq::TXERR {
+            Some(Self::Error)
+        } else if isr & irq::TXUDR == irq::TXUDR {
+            Some(Self::Underrun)
+        } else if isr & irq::TXACK == irq::TXACK {
+            Some(Self::Nack)
+        } else {
+            None
+        }
+    }
+}
+
+/// Errors that can occur while receiving a CEC message.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RxError {
+    /// A new byte was received before the application read the previous one
+    /// out of `RXDR`.
+    ///
+    /// Corresponds to [`irq::RXOVR`].
+    Overrun,
+    /// A data-bit waveform was detected with a bit rising error.
+    ///
+    /// Corresponds to [`irq::BRE`].
+    BitRisingError,
+    /// A data-bit waveform was detected with a short bit period error.
+    ///
+    /// Corresponds to [`irq::SBPE`].
+    ShortBitPeriodError,
+    /// A data-bit waveform was detected with a long bit period error.
+    ///
+    /// Corresponds to [`irq::LBPE`].
+    LongBitPeriodError,
+    /// No acknowledge was seen on the CEC line for a broadcast message, or
+    /// for a message not addressed to this device while in listen mode.
+    ///
+    /// Corresponds to [`irq::RXACK`].
+    Nack,
+    /// A blocking call gave up waiting for the peripheral to report an
+    /// event.
+    ///
+    /// Not a hardware-reported error; only produced by [`Cec`]'s blocking
+    /// helpers, never by [`CecState::on_interrupt`].
+    Timeout,
+}
+
+impl RxError {
+    /// Decode the first matching RX error out of a raw interrupt status
+    /// value, or `None` if it carries no RX error flags.
+    fn from_isr(isr: u32) -> Option<Self> {
+        if isr & irq::RXOVR == irq::RXOVR {
+            Some(Self::Overrun)
+        } else if isr & irq::BRE == irq::BRE {
+            Some(Self::BitRisingError)
+        } else if isr & irq::SBPE == irq::SBPE {
+            Some(Self::ShortBitPeriodError)
+        } else if isr & irq::LBPE == irq::LBPE {
+            Some(Self::LongBitPeriodError)
+        } else if isr & irq::RXACK == irq::RXACK {
+            Some(Self::Nack)
+        } else {
+            None
+        }
+    }
+}
+
+/// A received CEC message.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxMessage {
+    /// Logical address of the device that sent the message.
+    pub initiator: LogiAddr,
+    /// Logical address the message was sent to, or [`LogiAddr::Broadcast`].
+    pub destination: LogiAddr,
+    /// Number of blocks received, including the header block.
+    pub len: usize,
+}
+
+/// Maximum number of operand bytes in a single CEC message.
+///
+/// A CEC message is at most 16 blocks: 1 header block, 1 opcode block, and
+/// up to 14 operand blocks.
+pub const MAX_OPERANDS: usize = 14;
+
+/// Error returned when more than [`MAX_OPERANDS`] bytes are passed to
+/// [`Operands::try_from`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TooManyOperands;
+
+/// Operand bytes for a CEC message.
+///
+/// Bounded to [`MAX_OPERANDS`] bytes so that a [`Cec::send_message`] call can
+/// never exceed the 16-block CEC message limit.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Operands {
+    buf: [u8; MAX_OPERANDS],
+    len: u8,
+}
+
+impl Operands {
+    /// An empty set of operands.
+    pub const EMPTY: Self = Self {
+        buf: [0; MAX_OPERANDS],
+        len: 0,
+    };
+
+    /// Returns the operand bytes.
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+impl Default for Operands {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl TryFrom<&[u8]> for Operands {
+    type Error = TooManyOperands;
+
+    fn try_from(params: &[u8]) -> Result<Self, Self::Error> {
+        if params.len() > MAX_OPERANDS {
+            return Err(TooManyOperands);
+        }
+
+        let mut buf: [u8; MAX_OPERANDS] = [0; MAX_OPERANDS];
+        buf[..params.len()].copy_from_slice(params);
+        Ok(Self {
+            buf,
+            len: params.len() as u8,
+        })
+    }
+}
+
 /// Physical Address
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-#[allow(dead_code)]
 pub struct PhysAddr {
     addr: u32,
 }
 
+impl PhysAddr {
+    /// Build a physical address from its four nibbles (port numbers), each
+    /// `0..=0xF`.
+    ///
+    /// Returns `None` if any nibble is greater than `0xF`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stm32_cec::PhysAddr;
+    ///
+    /// let addr: PhysAddr = PhysAddr::from_nibbles(1, 2, 0, 0).unwrap();
+    /// assert_eq!(addr.as_u16(), 0x1200);
+    /// assert_eq!(PhysAddr::from_nibbles(0x10, 0, 0, 0), None);
+    /// ```
+    #[must_use]
+    pub const fn from_nibbles(a: u8, b: u8, c: u8, d: u8) -> Option<Self> {
+        if a > 0xF || b > 0xF || c > 0xF || d > 0xF {
+            return None;
+        }
+
+        Some(Self {
+            addr: ((a as u32) << 24) | ((b as u32) << 16) | ((c as u32) << 8) | (d as u32),
+        })
+    }
+
+    /// Build a physical address from its on-wire big-endian 2-byte form
+    /// (`0xABCD`, where each nibble is a port number).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stm32_cec::PhysAddr;
+    ///
+    /// let addr: PhysAddr = PhysAddr::from_u16(0x1200);
+    /// assert_eq!(addr.as_u16(), 0x1200);
+    /// ```
+    #[must_use]
+    pub const fn from_u16(raw: u16) -> Self {
+        let a: u8 = ((raw >> 12) & 0xF) as u8;
+        let b: u8 = ((raw >> 8) & 0xF) as u8;
+        let c: u8 = ((raw >> 4) & 0xF) as u8;
+        let d: u8 = (raw & 0xF) as u8;
+        Self {
+            addr: ((a as u32) << 24) | ((b as u32) << 16) | ((c as u32) << 8) | (d as u32),
+        }
+    }
+
+    /// Convert to the on-wire big-endian 2-byte form (`0xABCD`, where each
+    /// nibble is a port number).
+    #[must_use]
+    pub const fn as_u16(&self) -> u16 {
+        let a: u16 = ((self.addr >> 24) & 0xF) as u16;
+        let b: u16 = ((self.addr >> 16) & 0xF) as u16;
+        let c: u16 = ((self.addr >> 8) & 0xF) as u16;
+        let d: u16 = (self.addr & 0xF) as u16;
+        (a << 12) | (b << 8) | (c << 4) | d
+    }
+
+    /// Pack into the on-wire big-endian 2-byte form used by CEC messages
+    /// such as "Report Physical Address".
+    fn to_wire_bytes(self) -> [u8; 2] {
+        self.as_u16().to_be_bytes()
+    }
+}
+
 #[cfg(feature = "defmt")]
 impl defmt::Format for PhysAddr {
     fn format(&self, fmt: defmt::Formatter) {
@@ -231,11 +478,292 @@ impl TryFrom<u8> for LogiAddr {
     }
 }
 
+/// CEC device type, used to select candidate logical addresses for
+/// [`Cec::allocate_logical_address`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceType {
+    /// Television.
+    Tv,
+    /// Recording device.
+    RecordingDevice,
+    /// Tuner.
+    Tuner,
+    /// Playback device.
+    Playback,
+    /// Audio system.
+    AudioSystem,
+}
+
+impl DeviceType {
+    /// Candidate logical addresses to poll, in priority order, when
+    /// allocating an address for this device type.
+    const fn candidates(self) -> &'static [LogiAddr] {
+        match self {
+            Self::Tv => &[LogiAddr::Tv],
+            Self::RecordingDevice => &[LogiAddr::RecDev1, LogiAddr::RecDev2, LogiAddr::RecDev3],
+            Self::Tuner => &[
+                LogiAddr::Tuner1,
+                LogiAddr::Tuner2,
+                LogiAddr::Tuner3,
+                LogiAddr::Tuner4,
+            ],
+            Self::Playback => &[
+                LogiAddr::PlaybackDev,
+                LogiAddr::PlaybackDev2,
+                LogiAddr::PlaybackDev3,
+            ],
+            Self::AudioSystem => &[LogiAddr::AudioSys],
+        }
+    }
+
+    /// The device type byte as carried on the wire by "Report Physical
+    /// Address".
+    const fn cec_code(self) -> u8 {
+        match self {
+            Self::Tv => 0,
+            Self::RecordingDevice => 1,
+            Self::Tuner => 3,
+            Self::Playback => 4,
+            Self::AudioSystem => 5,
+        }
+    }
+}
+
+/// CEC opcodes used by the builder methods on [`Cec`].
+///
+/// This is not an exhaustive list of CEC opcodes. [`Cec::set_standby`]
+/// (`0x36`) and [`Cec::set_image_view_on`] (`0x04`) send their opcodes as
+/// raw bytes and have no variant here.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Opcode {
+    /// Active Source
+    ActiveSource = 0x82,
+    /// Give Physical Address
+    GivePhysicalAddress = 0x83,
+    /// Report Physical Address
+    ReportPhysicalAddress = 0x84,
+    /// Set OSD Name
+    SetOsdName = 0x47,
+    /// User Control Pressed
+    UserControlPressed = 0x44,
+    /// User Control Released
+    UserControlReleased = 0x45,
+}
+
+impl From<Opcode> for u8 {
+    #[inline]
+    fn from(opcode: Opcode) -> Self {
+        opcode as u8
+    }
+}
+
+/// User Control (remote-control) command codes, for
+/// [`Cec::user_control_pressed`].
+///
+/// This is not an exhaustive list of UI commands.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum UiCommand {
+    /// Select
+    Select = 0x00,
+    /// Up
+    Up = 0x01,
+    /// Down
+    Down = 0x02,
+    /// Left
+    Left = 0x03,
+    /// Right
+    Right = 0x04,
+    /// Play
+    Play = 0x44,
+    /// Stop
+    Stop = 0x45,
+}
+
+impl From<UiCommand> for u8 {
+    #[inline]
+    fn from(cmd: UiCommand) -> Self {
+        cmd as u8
+    }
+}
+
+/// In-flight TX byte cursor tracked by [`CecState`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct TxCursor {
+    opcode: u8,
+    params: Operands,
+    /// Index of the next data block to write to `TXDR`; `0` is the opcode.
+    idx: usize,
+}
+
+impl TxCursor {
+    fn data_blocks(&self) -> usize {
+        1 + self.params.as_slice().len()
+    }
+}
+
+/// Returned by [`CecState::start_message`] when a message is already being
+/// transmitted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxBusy;
+
+/// High-level events produced by [`CecState::on_interrupt`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The in-flight message finished transmitting successfully.
+    MessageSent,
+    /// A complete message was received.
+    MessageReceived(RxMessage),
+    /// The in-flight transmission failed.
+    TxError(TxError),
+    /// An in-flight reception failed; any partially-received message was
+    /// discarded.
+    RxError(RxError),
+}
+
+/// Non-blocking TX/RX state machine for the CEC peripheral.
+///
+/// [`CecState::on_interrupt`] is meant to be called from the CEC IRQ handler
+/// on every interrupt; it advances whichever transmission or reception is in
+/// flight and reports completions and errors as [`Event`]s, without blocking
+/// the core for the tens of milliseconds a CEC message can take on the wire.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CecState<const BASE: usize> {
+    regs: Regs<BASE>,
+    tx: Option<TxCursor>,
+    rx_buf: [u8; 16],
+    rx_len: usize,
+}
+
+impl<const BASE: usize> CecState<BASE> {
+    fn new(regs: Regs<BASE>) -> Self {
+        Self {
+            regs,
+            tx: None,
+            rx_buf: [0; 16],
+            rx_len: 0,
+        }
+    }
+
+    /// Start transmitting a message.
+    ///
+    /// Writes the header block and arms `TXSOM`; the remaining blocks are
+    /// fed to `TXDR` from [`CecState::on_interrupt`] as `TXBR` fires.
+    pub fn start_message(
+        &mut self,
+        src: LogiAddr,
+        dst: LogiAddr,
+        opcode: u8,
+        params: Operands,
+    ) -> Result<(), TxBusy> {
+        if self.tx.is_some() {
+            return Err(TxBusy);
+        }
+
+        self.regs.set_txdr((u8::from(src) << 4) | u8::from(dst));
+        self.regs.set_cr(Cr::SOM);
+        self.tx = Some(TxCursor {
+            opcode,
+            params,
+            idx: 0,
+        });
+        Ok(())
+    }
+
+    /// Send a CEC polling message: a header-only frame with both the
+    /// initiator and destination nibbles set to `addr`.
+    fn start_poll(&mut self, addr: LogiAddr) {
+        self.regs.set_txdr((u8::from(addr) << 4) | u8::from(addr));
+        self.regs.set_cr(Cr::SOM_EOM);
+    }
+
+    /// Give up on whatever transmission is in flight.
+    ///
+    /// Clears `TXSOM`/`TXEOM` to abort the hardware transmission and drops
+    /// the cursor, so a later [`CecState::start_message`] is not rejected
+    /// with [`TxBusy`] for a transmission no one is still waiting on.
+    fn abort_tx(&mut self) {
+        self.tx = None;
+        self.regs.set_cr(Cr::EN);
+    }
+
+    /// Advance the state machine in response to a CEC interrupt.
+    ///
+    /// Call this from the CEC IRQ handler. Returns `None` if the interrupt
+    /// carried no flags relevant to this engine, or advanced an in-flight
+    /// operation without completing it (e.g. a `TXBR` byte feed).
+    pub fn on_interrupt(&mut self) -> Option<Event> {
+        let isr: u32 = Regs::<BASE>::isr();
+        if isr == 0 {
+            return None;
+        }
+        Regs::<BASE>::set_isr(isr);
+
+        if let Some(err) = TxError::from_isr(isr) {
+            self.tx = None;
+            return Some(Event::TxError(err));
+        }
+
+        if isr & irq::TXBR == irq::TXBR {
+            if let Some(cursor) = self.tx.as_mut() {
+                if cursor.idx < cursor.data_blocks() {
+                    let byte: u8 = if cursor.idx == 0 {
+                        cursor.opcode
+                    } else {
+                        cursor.params.as_slice()[cursor.idx - 1]
+                    };
+                    self.regs.set_txdr(byte);
+                    cursor.idx += 1;
+                    if cursor.idx == cursor.data_blocks() {
+                        self.regs.set_cr(Cr::EOM);
+                    }
+                }
+            }
+        }
+
+        if isr & irq::TXEND == irq::TXEND {
+            self.tx = None;
+            return Some(Event::MessageSent);
+        }
+
+        if let Some(err) = RxError::from_isr(isr) {
+            self.rx_len = 0;
+            return Some(Event::RxError(err));
+        }
+
+        if isr & irq::RXBR == irq::RXBR && self.rx_len < self.rx_buf.len() {
+            self.rx_buf[self.rx_len] = self.regs.rxdr();
+            self.rx_len += 1;
+        }
+
+        if isr & irq::RXEND == irq::RXEND {
+            let header: u8 = self.rx_buf[0];
+            let msg: RxMessage = RxMessage {
+                initiator: LogiAddr::try_from(header >> 4).expect("nibble is always 0..=0xF"),
+                destination: LogiAddr::try_from(header & 0xF).expect("nibble is always 0..=0xF"),
+                len: self.rx_len,
+            };
+            self.rx_len = 0;
+            return Some(Event::MessageReceived(msg));
+        }
+
+        None
+    }
+}
+
 /// HDMI-CEC driver.
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Cec<const BASE: usize> {
-    regs: Regs<BASE>,
+    state: CecState<BASE>,
 }
 
 impl<const BASE: usize> Cec<BASE> {
@@ -281,36 +809,117 @@ impl<const BASE: usize> Cec<BASE> {
         regs.set_ier(irq::ALL);
         regs.set_cr(Cr::EN);
 
-        Cec { regs }
+        Cec {
+            state: CecState::new(regs),
+        }
     }
 
-    fn poll_isr() -> u32 {
-        // TODO: timeout
-        loop {
-            let isr: u32 = Regs::<BASE>::isr();
-            if isr != 0 {
-                Regs::<BASE>::set_isr(isr);
-                return isr;
+    /// Access the non-blocking TX/RX engine.
+    ///
+    /// Call [`CecState::on_interrupt`] on the returned reference from the
+    /// CEC IRQ handler to drive transmission and reception without blocking,
+    /// e.g. under RTIC or embassy. [`Cec`]'s other methods keep using this
+    /// same engine. While a blocking method waits, it consumes every event
+    /// the engine reports and drops the ones it is not waiting for: a
+    /// message received during [`Cec::send_message`] is discarded, as is a
+    /// transmit result reported during [`Cec::receive`].
+    pub fn state(&mut self) -> &mut CecState<BASE> {
+        &mut self.state
+    }
+
+    /// Number of [`CecState::on_interrupt`] polls a blocking helper spins
+    /// through before giving up with a [`TxError::Timeout`] /
+    /// [`RxError::Timeout`].
+    const WAIT_ATTEMPTS: u32 = 1_000_000;
+
+    /// Busy-wait for [`CecState::on_interrupt`] to report an event.
+    ///
+    /// Returns `None` if no event was reported within
+    /// [`Cec::WAIT_ATTEMPTS`] polls.
+    fn wait_for_event(&mut self) -> Option<Event> {
+        for _ in 0..Self::WAIT_ATTEMPTS {
+            if let Some(event) = self.state.on_interrupt() {
+                return Some(event);
             }
         }
+        None
     }
 
-    fn send_byte(&mut self, src: LogiAddr, dst: LogiAddr, data: u8) -> Result<(), u32> {
-        self.regs.set_txdr((u8::from(src) << 4) | u8::from(dst));
-        self.regs.set_cr(Cr::SOM);
+    fn send_byte(&mut self, src: LogiAddr, dst: LogiAddr, data: u8) -> Result<(), TxError> {
+        self.send_message(src, dst, data, Operands::EMPTY)
+    }
 
-        let isr: u32 = Self::poll_isr();
-        if isr & irq::TXBR == irq::TXBR {
-            self.regs.set_txdr(data);
-            self.regs.set_cr(Cr::EOM);
-            let isr: u32 = Self::poll_isr();
-            if isr & irq::TXEND == irq::TXEND {
-                Ok(())
-            } else {
-                Err(isr)
+    /// Send a complete CEC message.
+    ///
+    /// `opcode` and `params` make up the message's data blocks. Together
+    /// with the header block built from `src` and `dst`, this must not
+    /// exceed the 16-block CEC message limit, which [`Operands`] enforces.
+    ///
+    /// # Example
+    ///
+    /// Send "Standby" with no operands beyond the opcode.
+    ///
+    /// ```no_run
+    /// use stm32_cec::{Cec, LogiAddr, Operands};
+    ///
+    /// let mut cec = unsafe { stm32_cec::Cec::<0x40006C00>::new() };
+    /// cec.send_message(LogiAddr::PlaybackDev, LogiAddr::Tv, 0x36, Operands::EMPTY)?;
+    /// # Ok::<(), stm32_cec::TxError>(())
+    /// ```
+    pub fn send_message(
+        &mut self,
+        src: LogiAddr,
+        dst: LogiAddr,
+        opcode: u8,
+        params: Operands,
+    ) -> Result<(), TxError> {
+        if self.state.start_message(src, dst, opcode, params).is_err() {
+            return Err(TxError::Busy);
+        }
+
+        loop {
+            match self.wait_for_event() {
+                Some(Event::MessageSent) => return Ok(()),
+                Some(Event::TxError(err)) => return Err(err),
+                Some(Event::MessageReceived(_) | Event::RxError(_)) => {}
+                None => {
+                    self.state.abort_tx();
+                    return Err(TxError::Timeout);
+                }
+            }
+        }
+    }
+
+    /// Receive a CEC message.
+    ///
+    /// Blocks until a full message has been received into `buf` (header
+    /// included), or an error occurs. `buf[0]` is the header block, and
+    /// [`RxMessage::initiator`]/[`RxMessage::destination`] are parsed out of
+    /// it for convenience; only messages allowed through by the `OAR`/listen
+    /// mode configuration set up in [`Cec::new`] reach this point.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stm32_cec::Cec;
+    ///
+    /// let mut cec = unsafe { stm32_cec::Cec::<0x40006C00>::new() };
+    /// let mut buf: [u8; 16] = [0; 16];
+    /// let msg = cec.receive(&mut buf)?;
+    /// let opcode: Option<&u8> = buf[1..msg.len].first();
+    /// # Ok::<(), stm32_cec::RxError>(())
+    /// ```
+    pub fn receive(&mut self, buf: &mut [u8; 16]) -> Result<RxMessage, RxError> {
+        loop {
+            match self.wait_for_event() {
+                Some(Event::MessageReceived(msg)) => {
+                    buf[..msg.len].copy_from_slice(&self.state.rx_buf[..msg.len]);
+                    return Ok(msg);
+                }
+                Some(Event::RxError(err)) => return Err(err),
+                Some(Event::MessageSent | Event::TxError(_)) => {}
+                None => return Err(RxError::Timeout),
             }
-        } else {
-            Err(isr)
         }
     }
 
@@ -325,9 +934,9 @@ impl<const BASE: usize> Cec<BASE> {
     ///
     /// let mut cec = unsafe { stm32_cec::Cec::<0x40006C00>::new() };
     /// cec.set_standby(LogiAddr::Broadcast, LogiAddr::Broadcast)?;
-    /// # Ok::<(), u32>(())
+    /// # Ok::<(), stm32_cec::TxError>(())
     /// ```
-    pub fn set_standby(&mut self, src: LogiAddr, dst: LogiAddr) -> Result<(), u32> {
+    pub fn set_standby(&mut self, src: LogiAddr, dst: LogiAddr) -> Result<(), TxError> {
         self.send_byte(src, dst, 0x36)
     }
 
@@ -342,9 +951,274 @@ impl<const BASE: usize> Cec<BASE> {
     ///
     /// let mut cec = unsafe { stm32_cec::Cec::<0x40006C00>::new() };
     /// cec.set_image_view_on(LogiAddr::Broadcast, LogiAddr::Broadcast)?;
-    /// # Ok::<(), u32>(())
+    /// # Ok::<(), stm32_cec::TxError>(())
     /// ```
-    pub fn set_image_view_on(&mut self, src: LogiAddr, dst: LogiAddr) -> Result<(), u32> {
+    pub fn set_image_view_on(&mut self, src: LogiAddr, dst: LogiAddr) -> Result<(), TxError> {
         self.send_byte(src, dst, 0x04)
     }
+
+    /// Send a CEC polling message: a header-only frame with both the
+    /// initiator and destination nibbles set to `addr`.
+    ///
+    /// Returns `true` if the address was acknowledged (i.e. already taken),
+    /// `false` if it was not acknowledged (i.e. free).
+    fn poll_address(&mut self, addr: LogiAddr) -> bool {
+        self.state.start_poll(addr);
+
+        loop {
+            match self.wait_for_event() {
+                Some(Event::TxError(TxError::Nack)) => return false,
+                Some(Event::MessageSent | Event::TxError(_)) => return true,
+                Some(Event::MessageReceived(_) | Event::RxError(_)) => {}
+                // Treat an unresponsive bus the same as an acknowledged
+                // (taken) address: skip it rather than claim it blind.
+                None => return true,
+            }
+        }
+    }
+
+    /// Claim a free logical address for `dev_type`.
+    ///
+    /// Implements the CEC polling procedure: each candidate address for
+    /// `dev_type` is polled in turn, and the first one that goes
+    /// unacknowledged (i.e. is not already claimed by another device on the
+    /// bus) is written into `OAR` and returned. Falls back to
+    /// [`LogiAddr::Broadcast`] (unregistered) if every candidate is taken.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stm32_cec::{Cec, DeviceType};
+    ///
+    /// let mut cec = unsafe { stm32_cec::Cec::<0x40006C00>::new() };
+    /// let addr = cec.allocate_logical_address(DeviceType::Playback);
+    /// ```
+    pub fn allocate_logical_address(&mut self, dev_type: DeviceType) -> LogiAddr {
+        for &candidate in dev_type.candidates() {
+            if !self.poll_address(candidate) {
+                let oar: Cfgr = Regs::<BASE>::cfgr().set_oar(1u16 << u8::from(candidate));
+                self.state.regs.set_cfgr(oar);
+                return candidate;
+            }
+        }
+
+        LogiAddr::Broadcast
+    }
+
+    /// Announce this device as the active source.
+    ///
+    /// Broadcasts "Active Source" carrying `phys_addr`, telling other
+    /// devices on the bus (e.g. the TV and an AVR) to switch to this
+    /// device's HDMI input.
+    pub fn active_source(&mut self, src: LogiAddr, phys_addr: PhysAddr) -> Result<(), TxError> {
+        let params: Operands = Operands::try_from(phys_addr.to_wire_bytes().as_slice())
+            .expect("a physical address always fits in Operands");
+        self.send_message(
+            src,
+            LogiAddr::Broadcast,
+            u8::from(Opcode::ActiveSource),
+            params,
+        )
+    }
+
+    /// Ask `dst` to report its physical address.
+    pub fn give_physical_address(&mut self, src: LogiAddr, dst: LogiAddr) -> Result<(), TxError> {
+        self.send_message(
+            src,
+            dst,
+            u8::from(Opcode::GivePhysicalAddress),
+            Operands::EMPTY,
+        )
+    }
+
+    /// Broadcast this device's physical address and device type.
+    pub fn report_physical_address(
+        &mut self,
+        src: LogiAddr,
+        phys_addr: PhysAddr,
+        dev_type: DeviceType,
+    ) -> Result<(), TxError> {
+        let [hi, lo]: [u8; 2] = phys_addr.to_wire_bytes();
+        let params: Operands = Operands::try_from([hi, lo, dev_type.cec_code()].as_slice())
+            .expect("3 bytes always fits in Operands");
+        self.send_message(
+            src,
+            LogiAddr::Broadcast,
+            u8::from(Opcode::ReportPhysicalAddress),
+            params,
+        )
+    }
+
+    /// Send this device's on-screen-display name to `dst`.
+    ///
+    /// Build `name` with `Operands::try_from(name.as_bytes())`.
+    pub fn set_osd_name(
+        &mut self,
+        src: LogiAddr,
+        dst: LogiAddr,
+        name: Operands,
+    ) -> Result<(), TxError> {
+        self.send_message(src, dst, u8::from(Opcode::SetOsdName), name)
+    }
+
+    /// Tell `dst` that `cmd` was pressed on this device's remote control.
+    ///
+    /// Must eventually be followed by [`Cec::user_control_released`].
+    pub fn user_control_pressed(
+        &mut self,
+        src: LogiAddr,
+        dst: LogiAddr,
+        cmd: UiCommand,
+    ) -> Result<(), TxError> {
+        let params: Operands = Operands::try_from([u8::from(cmd)].as_slice())
+            .expect("1 byte always fits in Operands");
+        self.send_message(src, dst, u8::from(Opcode::UserControlPressed), params)
+    }
+
+    /// Tell `dst` that the remote control button pressed via
+    /// [`Cec::user_control_pressed`] was released.
+    pub fn user_control_released(&mut self, src: LogiAddr, dst: LogiAddr) -> Result<(), TxError> {
+        self.send_message(
+            src,
+            dst,
+            u8::from(Opcode::UserControlReleased),
+            Operands::EMPTY,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operands_accepts_up_to_max() {
+        let params: Operands = Operands::try_from([0xAB; MAX_OPERANDS].as_slice()).unwrap();
+        assert_eq!(params.as_slice(), [0xAB; MAX_OPERANDS]);
+    }
+
+    #[test]
+    fn operands_rejects_over_max() {
+        assert_eq!(
+            Operands::try_from([0u8; MAX_OPERANDS + 1].as_slice()),
+            Err(TooManyOperands)
+        );
+    }
+
+    #[test]
+    fn operands_empty_is_empty() {
+        assert_eq!(Operands::EMPTY.as_slice(), &[] as &[u8]);
+        assert_eq!(Operands::default().as_slice(), &[] as &[u8]);
+    }
+
+    #[test]
+    fn tx_error_from_isr_decodes_each_flag() {
+        assert_eq!(
+            TxError::from_isr(irq::ARBLST),
+            Some(TxError::ArbitrationLost)
+        );
+        assert_eq!(TxError::from_isr(irq::TXERR), Some(TxError::Error));
+        assert_eq!(TxError::from_isr(irq::TXUDR), Some(TxError::Underrun));
+        assert_eq!(TxError::from_isr(irq::TXACK), Some(TxError::Nack));
+        assert_eq!(TxError::from_isr(irq::TXEND), None);
+        assert_eq!(TxError::from_isr(irq::TXBR), None);
+    }
+
+    #[test]
+    fn tx_error_from_isr_priority() {
+        // ARBLST takes priority over a simultaneously-set TXACK.
+        assert_eq!(
+            TxError::from_isr(irq::ARBLST | irq::TXACK),
+            Some(TxError::ArbitrationLost)
+        );
+    }
+
+    #[test]
+    fn rx_error_from_isr_decodes_each_flag() {
+        assert_eq!(RxError::from_isr(irq::RXOVR), Some(RxError::Overrun));
+        assert_eq!(RxError::from_isr(irq::BRE), Some(RxError::BitRisingError));
+        assert_eq!(
+            RxError::from_isr(irq::SBPE),
+            Some(RxError::ShortBitPeriodError)
+        );
+        assert_eq!(
+            RxError::from_isr(irq::LBPE),
+            Some(RxError::LongBitPeriodError)
+        );
+        assert_eq!(RxError::from_isr(irq::RXACK), Some(RxError::Nack));
+        assert_eq!(RxError::from_isr(irq::RXEND), None);
+        assert_eq!(RxError::from_isr(irq::RXBR), None);
+    }
+
+    #[test]
+    fn rx_error_from_isr_priority() {
+        // RXOVR takes priority over a simultaneously-set RXACK.
+        assert_eq!(
+            RxError::from_isr(irq::RXOVR | irq::RXACK),
+            Some(RxError::Overrun)
+        );
+    }
+
+    #[test]
+    fn device_type_candidates_match_cec_addresses() {
+        assert_eq!(DeviceType::Tv.candidates(), [LogiAddr::Tv]);
+        assert_eq!(
+            DeviceType::RecordingDevice.candidates(),
+            [LogiAddr::RecDev1, LogiAddr::RecDev2, LogiAddr::RecDev3]
+        );
+        assert_eq!(
+            DeviceType::Tuner.candidates(),
+            [
+                LogiAddr::Tuner1,
+                LogiAddr::Tuner2,
+                LogiAddr::Tuner3,
+                LogiAddr::Tuner4
+            ]
+        );
+        assert_eq!(
+            DeviceType::Playback.candidates(),
+            [
+                LogiAddr::PlaybackDev,
+                LogiAddr::PlaybackDev2,
+                LogiAddr::PlaybackDev3
+            ]
+        );
+        assert_eq!(DeviceType::AudioSystem.candidates(), [LogiAddr::AudioSys]);
+    }
+
+    #[test]
+    fn device_type_cec_code_matches_report_physical_address_encoding() {
+        assert_eq!(DeviceType::Tv.cec_code(), 0);
+        assert_eq!(DeviceType::RecordingDevice.cec_code(), 1);
+        assert_eq!(DeviceType::Tuner.cec_code(), 3);
+        assert_eq!(DeviceType::Playback.cec_code(), 4);
+        assert_eq!(DeviceType::AudioSystem.cec_code(), 5);
+    }
+
+    #[test]
+    fn phys_addr_from_nibbles_round_trips_through_u16() {
+        let addr: PhysAddr = PhysAddr::from_nibbles(0x1, 0x2, 0x3, 0x4).unwrap();
+        assert_eq!(addr.as_u16(), 0x1234);
+    }
+
+    #[test]
+    fn phys_addr_from_nibbles_rejects_out_of_range_nibble() {
+        assert_eq!(PhysAddr::from_nibbles(0x10, 0x0, 0x0, 0x0), None);
+        assert_eq!(PhysAddr::from_nibbles(0x0, 0x0, 0x0, 0x10), None);
+    }
+
+    #[test]
+    fn phys_addr_from_u16_round_trips_through_as_u16() {
+        assert_eq!(PhysAddr::from_u16(0xABCD).as_u16(), 0xABCD);
+        assert_eq!(PhysAddr::from_u16(0x0000).as_u16(), 0x0000);
+        assert_eq!(PhysAddr::from_u16(0xFFFF).as_u16(), 0xFFFF);
+    }
+
+    #[test]
+    fn phys_addr_from_u16_and_from_nibbles_agree() {
+        assert_eq!(
+            PhysAddr::from_u16(0x1234),
+            PhysAddr::from_nibbles(0x1, 0x2, 0x3, 0x4).unwrap()
+        );
+    }
 }
@@ -12,6 +12,9 @@ impl Cr {
     pub(crate) const EN: Self = Self::DEFAULT.set_en(true);
     pub(crate) const SOM: Self = Self::EN.set_txsom();
     pub(crate) const EOM: Self = Self::EN.set_txeom();
+    /// Start and end of message set together, for a single-block (header
+    /// only) message such as a CEC polling message.
+    pub(crate) const SOM_EOM: Self = Self::EN.set_txsom().set_txeom();
 
     /// Returns `true` if the CEC peripheral is enabled.
     #[must_use]